@@ -1,13 +1,24 @@
-use std::{fmt, ptr::eq};
+use std::{fmt, ops::Add, ptr::eq, str::FromStr};
 use itertools::iproduct;
 
 use crate::utils::read_string_lines;
 
 pub fn solution18 () {
-    let input: Vec<SnailfishNumber> = read_string_lines("src/data/solution18.txt").iter()
-        .map(String::as_str)
-        .map(parse_snailfish_number)
+    let input: Result<Vec<SnailfishNumber>, _> = read_string_lines("src/data/solution18.txt").iter()
+        .enumerate()
+        .map(|(line_num, line)| line.parse::<SnailfishNumber>()
+            .map_err(|err| format!("Line {}: {}", line_num + 1, err))
+        )
         .collect();
+
+    let input = match input {
+        Ok(input) => input,
+        Err(err) => {
+            println!("Failed to parse solution18.txt: {}", err);
+            return;
+        }
+    };
+
     println!("{}", solution18a(&input));
     println!("{}", solution18b(&input));
 }
@@ -16,7 +27,7 @@ fn solution18a(input: &[SnailfishNumber]) -> u32 {
     // Just add all of the Snailfish numbers together and determine magnitude
     input.iter()
         .cloned()
-        .reduce(add_numbers)
+        .reduce(|left, right| left + right)
         .expect("Input data is empty of valid Snailfish numbers")
         .magnitude()
 }
@@ -25,275 +36,295 @@ fn solution18b(input: &[SnailfishNumber]) -> u32 {
     // Cartesian product to find each pair of numbers
     iproduct!(input, input)
         // Numbers must be different from each other
-        .filter(|(left, right)| !eq(*left, *right))    
+        .filter(|(left, right)| !eq(*left, *right))
         // Find magnitude of their sum
-        .map(|(left, right)|
-            add_numbers(left.clone(), right.clone()).magnitude()
-        )
+        .map(|(left, right)| (left.clone() + right.clone()).magnitude())
         // We are interested in only the biggest result
         .max()
         .expect("Input data is empty of valid Snailfish numbers")
 }
 
-fn add_numbers(left: SnailfishNumber, right: SnailfishNumber) -> SnailfishNumber {
-    let mut combined = SnailfishNumber(
-        Box::new(
-            Pair {
-                left: Node::Pair(left.0),
-                right: Node::Pair(right.0)
-            }
-        )
-    );
-
-    // Check for explodes and splits until none are required
-    while combined.0.try_explode_children(1).exploded || combined.0.try_split_children() { }
-
-    combined
-}
-
 const SPLIT_LIMIT: u32 = 10;
-const OUTER_PAIR_LIMIT: u32 = 4;
+const OUTER_PAIR_LIMIT: u8 = 4;
 
-trait Magnitude { 
+pub trait Magnitude {
     fn magnitude(&self) -> u32;
 }
 
-#[derive(Clone)]
-struct SnailfishNumber(Box<Pair>);
-
-impl fmt::Debug for SnailfishNumber {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Number {:?}", self.0)
-    }
-}
+/// A Snailfish number stored as its leaves, left to right, each paired with its nesting depth
+/// (the number of enclosing pairs). Addition, explode, split and magnitude all reduce to scans
+/// or stack folds over this list, so none of them need the recursion a tree representation would.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnailfishNumber(Vec<(u32, u8)>);
 
 impl Magnitude for SnailfishNumber {
     fn magnitude(&self) -> u32 {
-        self.0.magnitude()
+        // A stack-based fold: a leaf only ever combines with the entry immediately to its left
+        // once that entry shares its depth, which is exactly when the two are siblings under the
+        // same pair. Collapsing immediately keeps the stack's top always representing the deepest
+        // adjacent pair still to be combined, so each element is pushed and popped at most once.
+        let mut stack: Vec<(u32, u8)> = Vec::new();
+        for &(value, depth) in &self.0 {
+            let mut collapsed = (value, depth);
+            while matches!(stack.last(), Some(&(_, top_depth)) if top_depth == collapsed.1) {
+                let (left_value, _) = stack.pop().unwrap();
+                collapsed = (3 * left_value + 2 * collapsed.0, collapsed.1 - 1);
+            }
+            stack.push(collapsed);
+        }
+        stack[0].0
     }
 }
 
-#[derive(Clone)]
-struct Pair {
-    left: Node,
-    right: Node,
-}
+impl SnailfishNumber {
+    /// Runs the explode/split reduction loop until neither applies any more.
+    pub fn reduce(&mut self) {
+        while self.try_reduce_step().is_some() { }
+    }
 
-impl fmt::Debug for Pair {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{:?} , {:?}]", self.left, self.right)
+    /// Like `reduce`, but returns the action and resulting number after each individual
+    /// explode or split, rather than only the final reduced number.
+    ///
+    /// Only exercised by tests today; this crate has no library surface for an external caller
+    /// to reach it through, so the lint is suppressed rather than faked with an unused `pub` API.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn reduce_steps(&mut self) -> Vec<(ReduceAction, SnailfishNumber)> {
+        let mut steps = Vec::new();
+        while let Some(action) = self.try_reduce_step() {
+            steps.push((action, self.clone()));
+        }
+        steps
     }
-}
 
-impl Magnitude for Pair {
-    fn magnitude(&self) -> u32 {
-        self.left.magnitude() * 3 + self.right.magnitude() * 2
+    fn try_reduce_step(&mut self) -> Option<ReduceAction> {
+        if let Some(index) = self.try_explode() {
+            return Some(ReduceAction::Explode { index });
+        }
+        self.try_split().map(|value| ReduceAction::Split { value })
     }
-}
 
-#[derive(Clone)]
-enum Node {
-    Pair(Box<Pair>),
-    Value(u32)
-}
+    fn combine(mut self, mut other: Self) -> Self {
+        self.0.iter_mut().for_each(|(_, depth)| *depth += 1);
+        other.0.iter_mut().for_each(|(_, depth)| *depth += 1);
+        self.0.extend(other.0);
+        self
+    }
 
-impl fmt::Debug for Node {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self {
-            Node::Value(value) => write!(f, "{}", value),
-            Node::Pair(pair) => pair.fmt(f)
+    /// Returns the leaf index of the exploding pair's left value, if any.
+    fn try_explode(&mut self) -> Option<usize> {
+        const EXPLODE_DEPTH: u8 = OUTER_PAIR_LIMIT + 1;
+        let left_idx = self.0.iter().position(|&(_, depth)| depth >= EXPLODE_DEPTH)?;
+        let (left_value, depth) = self.0[left_idx];
+        let (right_value, _) = self.0[left_idx + 1];
+        if left_idx > 0 {
+            self.0[left_idx - 1].0 += left_value;
         }
+        if let Some((value, _)) = self.0.get_mut(left_idx + 2) {
+            *value += right_value;
+        }
+        self.0.splice(left_idx..=left_idx + 1, [(0, depth - 1)]);
+        Some(left_idx)
     }
-}
 
-impl Magnitude for Node {
-    fn magnitude(&self) -> u32 {
-        match self {
-            Node::Pair(pair) => pair.magnitude(), 
-            Node::Value(value) => *value
-        }
+    /// Returns the value that split, if any.
+    fn try_split(&mut self) -> Option<u32> {
+        let idx = self.0.iter().position(|&(value, _)| value >= SPLIT_LIMIT)?;
+        let (value, depth) = self.0[idx];
+        let left_value = value / 2;
+        self.0.splice(idx..=idx, [(left_value, depth + 1), (value - left_value, depth + 1)]);
+        Some(value)
     }
 }
 
-struct ExplodeResult {
-    exploded: bool,
-    carry_value: Option<ExplodeCarryValue>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduceAction {
+    /// An exploding pair was found; `index` is the position of its left value among the
+    /// number's leaves, read left to right, before the explode was applied.
+    Explode { index: usize },
+    /// A leaf `value` of 10 or greater was split into two leaves.
+    Split { value: u32 },
 }
 
-struct ExplodeCarryValue {
-    direction: Direction,
-    value: u32
+impl Add for SnailfishNumber {
+    type Output = SnailfishNumber;
+
+    fn add(self, rhs: SnailfishNumber) -> SnailfishNumber {
+        let mut combined = self.combine(rhs);
+        combined.reduce();
+        combined
+    }
 }
 
-#[derive(PartialEq)]
-enum Direction {Left, Right}
-
-impl Pair {
-    fn try_explode_children(&mut self, outer_pairs: u32) -> ExplodeResult {
-        // At outer pair limit, any children that are pairs are ready to explode
-        if outer_pairs >= OUTER_PAIR_LIMIT {
-            // Important to check left then right separately, due to slight differences in propagation
-            // NOTE: There is an assumption that an exploding pair will have values as both children, as regular
-            // exploding after each add should not result in a pair reaching the depth limit while having further
-            // pairs beneath them
-            let old_left = std::mem::replace(&mut self.left, Node::Value(0));
-            if let Node::Pair(pair) = &old_left {
-                self.right.accept_carry_value(&ExplodeCarryValue{
-                    direction: Direction::Right,
-                    value: pair.right.force_as_value()
-                });
-                return ExplodeResult {
-                    exploded: true,
-                    carry_value: Some(ExplodeCarryValue{
-                        direction: Direction::Left,
-                        value: pair.left.force_as_value()
-                    })
-                };
-            } else {
-                self.left = old_left;
-            }
-            
-            let old_right = std::mem::replace(&mut self.right, Node::Value(0));
-            if let Node::Pair(pair) = &old_right {
-                self.left.accept_carry_value(&ExplodeCarryValue{
-                    direction: Direction::Left,
-                    value: pair.left.force_as_value()
-                });
-                return ExplodeResult {
-                    exploded: true,
-                    carry_value: Some(ExplodeCarryValue{
-                        direction: Direction::Right,
-                        value: pair.right.force_as_value()
-                    })
-                };
-            } else {
-                self.right = old_right
-            }
-        } else {
-            // Otherwise a recursive search through child pairs. Propagate upwards any reports of
-            // explosions. An explosion may also come with a left- or right- fragment that needs to
-            // be shifted left or right along the tree. In practical terms this involves moving the
-            // fragment up the tree and then down again.
-            if let Node::Pair(pair) = &mut self.left {
-                let mut explode_attempt = pair.try_explode_children(outer_pairs+1);
-                if explode_attempt.exploded {
-                    if let Some(ExplodeCarryValue{direction: Direction::Right, ..}) = &explode_attempt.carry_value {
-                        // Safe to unwrap as we already matched against Some above
-                        self.right.accept_carry_value(&explode_attempt.carry_value.unwrap());
-                        explode_attempt.carry_value = None;
-                    }
-                    return explode_attempt;
-                }
-            }
-            if let Node::Pair(pair) = &mut self.right {
-                let mut explode_attempt = pair.try_explode_children(outer_pairs+1);
-                if explode_attempt.exploded {
-                    if let Some(ExplodeCarryValue{direction: Direction::Left, ..}) = &explode_attempt.carry_value {
-                        self.left.accept_carry_value(&explode_attempt.carry_value.unwrap());
-                        explode_attempt.carry_value = None;
-                    }
-                    return explode_attempt;
-                }
-            }
-        }
-        // If reached, no explodes are required
-        ExplodeResult{
-            exploded: false,
-            carry_value: None
-        }                
+impl Add<&SnailfishNumber> for SnailfishNumber {
+    type Output = SnailfishNumber;
+
+    fn add(self, rhs: &SnailfishNumber) -> SnailfishNumber {
+        self + rhs.clone()
     }
+}
 
-    fn try_split_children(&mut self) -> bool {
-        for child in [&mut self.left, &mut self.right] {
-            if match child {
-                Node::Pair(pair) => pair.try_split_children(),
-                Node::Value(value) if (*value >= SPLIT_LIMIT) => {
-                    child.split();
-                    true
-                },
-                _ => false
-            } {
-                return true;
-            }
+#[derive(Debug, PartialEq, Eq)]
+pub enum SnailfishError {
+    /// An unexpected character was found while scanning the input.
+    UnexpectedChar { char: char, offset: usize },
+    /// A `[...]` pair is missing its closing bracket, or the input doesn't open with one.
+    UnbalancedBrackets,
+    /// A pair had no top-level comma to split its two children on.
+    EmptyPair,
+    /// A leaf value failed to parse as a `u32`.
+    InvalidValue { text: String, offset: usize },
+}
+
+impl fmt::Display for SnailfishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnailfishError::UnexpectedChar { char, offset } =>
+                write!(f, "unexpected character '{}' at offset {}", char, offset),
+            SnailfishError::UnbalancedBrackets =>
+                write!(f, "unbalanced brackets in pair"),
+            SnailfishError::EmptyPair =>
+                write!(f, "pair has no top-level comma separating its children"),
+            SnailfishError::InvalidValue { text, offset } =>
+                write!(f, "invalid value '{}' at offset {}", text, offset),
         }
-        false // No splits required
     }
 }
 
-impl Node {
-    fn force_as_value(&self) -> u32 {
-        if let Self::Value(value) = self {*value} else { dbg!(&self); unreachable!("Forcing non-value node to value!")}
-    }
+impl std::error::Error for SnailfishError {}
 
-    fn split(&mut self) {
-        match self {
-            Self::Value(value) => {
-                let left_val = *value / 2; // Left half rounds down (integer division)
-                *self = Self::Pair(
-                    Box::new(
-                        Pair {
-                            left: Self::Value(left_val),
-                            right: Self::Value(*value - left_val)
+impl FromStr for SnailfishNumber {
+    type Err = SnailfishError;
+
+    // Scans the input once, left to right, tracking bracket nesting directly as leaf depth
+    // rather than building a tree first: a leaf's depth is just how many brackets are open
+    // when it's read, and a pair closes successfully only once it has seen its own comma.
+    fn from_str(num_ser: &str) -> Result<Self, Self::Err> {
+        if !num_ser.starts_with('[') {
+            return Err(SnailfishError::UnbalancedBrackets);
+        }
+
+        let mut leaves = Vec::new();
+        let mut open_pairs: Vec<bool> = Vec::new();
+        let mut chars = num_ser.char_indices().peekable();
+
+        while let Some(&(idx, ch)) = chars.peek() {
+            match ch {
+                '[' => {
+                    open_pairs.push(false);
+                    chars.next();
+                },
+                ']' => match open_pairs.pop() {
+                    Some(true) => { chars.next(); },
+                    Some(false) => return Err(SnailfishError::EmptyPair),
+                    None => return Err(SnailfishError::UnexpectedChar { char: ch, offset: idx }),
+                },
+                ',' => match open_pairs.last_mut() {
+                    Some(seen_comma) if !*seen_comma => {
+                        *seen_comma = true;
+                        chars.next();
+                    },
+                    _ => return Err(SnailfishError::UnexpectedChar { char: ch, offset: idx }),
+                },
+                digit if digit.is_ascii_digit() => {
+                    let start = idx;
+                    let mut end = idx;
+                    chars.next();
+                    while let Some(&(i, c)) = chars.peek() {
+                        if c.is_ascii_digit() {
+                            end = i;
+                            chars.next();
+                        } else {
+                            break;
                         }
-                    )
-                );
+                    }
+                    let text = &num_ser[start..=end];
+                    let value = text.parse::<u32>()
+                        .map_err(|_| SnailfishError::InvalidValue { text: text.to_string(), offset: start })?;
+                    leaves.push((value, open_pairs.len() as u8));
+                },
+                _ => return Err(SnailfishError::UnexpectedChar { char: ch, offset: idx }),
+            }
+        }
 
-            },
-            _ => unimplemented!("Only value nodes are splittable!")
+        if !open_pairs.is_empty() {
+            return Err(SnailfishError::UnbalancedBrackets);
         }
-    }
 
-    fn accept_carry_value(&mut self, carry_value: &ExplodeCarryValue) {
-        match (self, carry_value) {
-            (Node::Value(my_value), ExplodeCarryValue{value: carried, ..}) => {
-                *my_value += carried;
-            },
-            (Node::Pair(pair), ExplodeCarryValue{direction: Direction::Right, ..}) => {
-                pair.left.accept_carry_value(carry_value)
-            },
-            (Node::Pair(pair), ExplodeCarryValue{direction: Direction::Left, ..}) => {
-                pair.right.accept_carry_value(carry_value)
-            }           
-        };
+        Ok(SnailfishNumber(leaves))
     }
 }
 
-fn parse_snailfish_number(num_ser: &str) -> SnailfishNumber {
-    SnailfishNumber(
-        Box::new(
-            parse_pair(&num_ser[1..num_ser.len()-1])
-        )
-    )    
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn parse_node(node_ser: &str) -> Node {
-    if !node_ser.starts_with('[') {
-        Node::Value(node_ser.parse::<u32>().expect("Invalid Snailfish number"))
-    } else {
-        Node::Pair(
-            Box::new(parse_pair(&node_ser[1..node_ser.len()-1]))
-        )
+    #[test]
+    fn parse_errors_match_expected_variants() {
+        assert_eq!(
+            "1,2]".parse::<SnailfishNumber>().unwrap_err(),
+            SnailfishError::UnbalancedBrackets
+        );
+        assert_eq!(
+            "[12]".parse::<SnailfishNumber>().unwrap_err(),
+            SnailfishError::EmptyPair
+        );
+        assert_eq!(
+            "[1,2]]".parse::<SnailfishNumber>().unwrap_err(),
+            SnailfishError::UnexpectedChar { char: ']', offset: 5 }
+        );
+        assert_eq!(
+            "[4294967296,2]".parse::<SnailfishNumber>().unwrap_err(),
+            SnailfishError::InvalidValue { text: "4294967296".to_string(), offset: 1 }
+        );
     }
-}
 
-fn parse_pair(pair_ser: &str) -> Pair {
-    let comma_pos = find_comma(pair_ser);
-    Pair{
-        left: parse_node(&pair_ser[..comma_pos]),
-        right: parse_node(&pair_ser[(comma_pos+1)..])
+    #[test]
+    fn explode_matches_canonical_examples() {
+        let cases = [
+            ("[[[[[9,8],1],2],3],4]", "[[[[0,9],2],3],4]"),
+            ("[7,[6,[5,[4,[3,2]]]]]", "[7,[6,[5,[7,0]]]]"),
+            ("[[6,[5,[4,[3,2]]]],1]", "[[6,[5,[7,0]]],3]"),
+            ("[[3,[2,[1,[7,3]]]],[6,[5,[4,[3,2]]]]]", "[[3,[2,[8,0]]],[9,[5,[4,[3,2]]]]]"),
+            ("[[3,[2,[8,0]]],[9,[5,[4,[3,2]]]]]", "[[3,[2,[8,0]]],[9,[5,[7,0]]]]"),
+        ];
+        for (before, after) in cases {
+            let mut number: SnailfishNumber = before.parse().unwrap();
+            assert!(number.try_explode().is_some());
+            assert_eq!(number, after.parse().unwrap());
+        }
     }
-}
 
-fn find_comma(pair_ser: &str) -> usize {
-    let mut stack_count = 0;
-    for (idx, char) in pair_ser.chars().enumerate() {
-        match char {
-            ',' if stack_count == 0 => return idx,
-            '[' => stack_count += 1,
-            ']' if stack_count == 0 => panic!("Unexpected pair finish!"),
-            ']' => stack_count -= 1,
-            _ => ()
+    #[test]
+    fn magnitude_matches_canonical_examples() {
+        let cases = [
+            ("[[1,2],[[3,4],5]]", 143),
+            ("[[[[0,7],4],[[7,8],[6,0]]],[8,1]]", 1384),
+            ("[[[[1,1],[2,2]],[3,3]],[4,4]]", 445),
+            ("[[[[3,0],[5,3]],[4,4]],[5,5]]", 791),
+            ("[[[[5,0],[7,4]],[5,5]],[6,6]]", 1137),
+            ("[[[[8,7],[7,7]],[[8,6],[7,7]]],[[[0,7],6],[[8,7],7]]]", 3560),
+        ];
+        for (number_ser, expected_magnitude) in cases {
+            let number: SnailfishNumber = number_ser.parse().unwrap();
+            assert_eq!(number.magnitude(), expected_magnitude);
         }
     }
-    unreachable!("Failed to find comma in pair!");
-}
\ No newline at end of file
+
+    #[test]
+    fn reduce_steps_matches_full_reduction_example() {
+        let mut number: SnailfishNumber = "[[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]".parse().unwrap();
+        let expected = [
+            (ReduceAction::Explode { index: 0 }, "[[[[0,7],4],[7,[[8,4],9]]],[1,1]]"),
+            (ReduceAction::Explode { index: 4 }, "[[[[0,7],4],[15,[0,13]]],[1,1]]"),
+            (ReduceAction::Split { value: 15 }, "[[[[0,7],4],[[7,8],[0,13]]],[1,1]]"),
+            (ReduceAction::Split { value: 13 }, "[[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]"),
+            (ReduceAction::Explode { index: 6 }, "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]"),
+        ];
+        let steps = number.reduce_steps();
+        assert_eq!(steps.len(), expected.len());
+        for ((action, result), (expected_action, expected_ser)) in steps.iter().zip(expected.iter()) {
+            assert_eq!(action, expected_action);
+            assert_eq!(result, &expected_ser.parse::<SnailfishNumber>().unwrap());
+        }
+    }
+}